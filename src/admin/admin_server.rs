@@ -4,14 +4,18 @@ use std::convert::Infallible;
 use std::net::SocketAddr;
 use std::rc::Rc;
 use std::sync::Arc;
+use futures::{stream, StreamExt};
 use hyper::{Body, Request, Response, Server, StatusCode, Method, header};
 use hyper::service::{make_service_fn, service_fn};
 use log::info;
 use rust_embed::RustEmbed;
 use serde_json::json;
 use tokio::join;
+use tokio::sync::broadcast::error::RecvError;
 
 use crate::network::p2p_network::{P2PNetwork, Client};
+use crate::protocols::drop::MAX_TRANSFER_SIZE;
+use crate::responses::list_response::ListEventType;
 
 static NOTFOUND: &[u8] = b"Not found";
 static INDEX: &str = "index.html";
@@ -64,7 +68,11 @@ impl AdminServer {
     match (req.method(), req.uri().path()) {
       (&Method::GET, "/") | (&Method::GET, "/index.html") => simple_file_send(INDEX, port).await,
       (&Method::POST, "/api/send_ps") => api_send_ps(network).await,
+      (&Method::POST, "/api/send_file") => api_send_file(req, network).await,
       (&Method::GET, "/api/events") => get_events(network).await,
+      (&Method::GET, "/api/events/stream") => get_events_stream(network).await,
+      (&Method::GET, "/api/identity") => get_identity(network).await,
+      (&Method::GET, "/api/metrics") => get_metrics(network).await,
       _ => Ok(not_found()),
     }
   }
@@ -91,6 +99,171 @@ impl AdminServer {
     Ok(api_response)
   }
 
+  struct SendFileUpload {
+    target_peer: String,
+    filename: String,
+    data: Vec<u8>,
+  }
+
+  async fn api_send_file(req: Request<Body>, mut network: Client) -> Result<Response<Body>> {
+    let boundary = req
+      .headers()
+      .get(header::CONTENT_TYPE)
+      .and_then(|v| v.to_str().ok())
+      .and_then(multipart_boundary);
+
+    let boundary = match boundary {
+      Some(b) => b,
+      None => return Ok(bad_request("expected multipart/form-data with a boundary")),
+    };
+
+    let body_bytes = match read_body_with_limit(req.into_body(), MAX_TRANSFER_SIZE).await {
+      Ok(bytes) => bytes,
+      Err(e) => return Ok(bad_request(&e)),
+    };
+    let upload = match parse_send_file_upload(&body_bytes, &boundary) {
+      Ok(u) => u,
+      Err(e) => return Ok(bad_request(&e)),
+    };
+
+    let result = network.send_file(upload.target_peer, upload.filename, upload.data).await;
+    let (status, data) = match result {
+      Ok(()) => (StatusCode::OK, json!({ "status": "sent" })),
+      Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, json!({ "error": e })),
+    };
+    let response = Response::builder()
+      .status(status)
+      .header(header::CONTENT_TYPE, "application/json")
+      .body(Body::from(serde_json::to_string(&data)?))?;
+    Ok(response)
+  }
+
+  // Accumulates a request body up to `limit` bytes, bailing out as soon as
+  // it's exceeded instead of buffering an unbounded upload before checking.
+  async fn read_body_with_limit(mut body: Body, limit: u64) -> std::result::Result<Vec<u8>, String> {
+    let mut data = Vec::new();
+    while let Some(chunk) = body.next().await {
+      let chunk = chunk.map_err(|e| format!("error reading request body: {}", e))?;
+      if data.len() as u64 + chunk.len() as u64 > limit {
+        return Err(format!("request body exceeds maximum allowed size of {} bytes", limit));
+      }
+      data.extend_from_slice(&chunk);
+    }
+    Ok(data)
+  }
+
+  fn multipart_boundary(content_type: &str) -> Option<String> {
+    let (mime, params) = content_type.split_once(';')?;
+    if mime.trim() != "multipart/form-data" {
+      return None;
+    }
+    params
+      .split(';')
+      .find_map(|param| param.trim().strip_prefix("boundary="))
+      .map(|b| b.trim_matches('"').to_string())
+  }
+
+  // Hand-rolled multipart parser scoped to the two fields the send-file form submits.
+  fn parse_send_file_upload(body: &[u8], boundary: &str) -> std::result::Result<SendFileUpload, String> {
+    let delimiter = format!("--{}", boundary);
+    let mut target_peer = None;
+    let mut file_part = None;
+
+    for part in split_multipart_parts(body, delimiter.as_bytes()) {
+      let (headers, content) = match split_once_bytes(part, b"\r\n\r\n") {
+        Some(split) => split,
+        None => continue,
+      };
+      let headers = String::from_utf8_lossy(headers);
+      let name = match field_name(&headers) {
+        Some(name) => name,
+        None => continue,
+      };
+      let content = content.strip_suffix(b"\r\n").unwrap_or(content);
+
+      if name == "target_peer" {
+        target_peer = Some(String::from_utf8_lossy(content).into_owned());
+      } else if name == "file" {
+        let filename = file_name(&headers).unwrap_or_else(|| "upload".to_string());
+        file_part = Some((filename, content.to_vec()));
+      }
+    }
+
+    let target_peer = target_peer.ok_or("missing \"target_peer\" field")?;
+    let (filename, data) = file_part.ok_or("missing \"file\" field")?;
+    Ok(SendFileUpload { target_peer, filename, data })
+  }
+
+  fn split_multipart_parts<'a>(body: &'a [u8], delimiter: &[u8]) -> Vec<&'a [u8]> {
+    let mut parts = Vec::new();
+    let mut rest = body;
+    while let Some(pos) = find_bytes(rest, delimiter) {
+      rest = &rest[pos + delimiter.len()..];
+      let end = find_bytes(rest, delimiter).unwrap_or(rest.len());
+      let candidate = &rest[..end];
+      let candidate = candidate.strip_prefix(b"\r\n").unwrap_or(candidate);
+      if !candidate.is_empty() && candidate != b"--" {
+        parts.push(candidate);
+      }
+    }
+    parts
+  }
+
+  // Scans for needle's first byte before comparing the full slice, instead of
+  // re-comparing needle.len() bytes at every offset.
+  fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.is_empty() || haystack.len() < needle.len() {
+      return None;
+    }
+    let first = needle[0];
+    let mut start = 0;
+    while let Some(offset) = haystack[start..].iter().position(|&b| b == first) {
+      let pos = start + offset;
+      if haystack.len() - pos < needle.len() {
+        return None;
+      }
+      if &haystack[pos..pos + needle.len()] == needle {
+        return Some(pos);
+      }
+      start = pos + 1;
+    }
+    None
+  }
+
+  fn split_once_bytes<'a>(data: &'a [u8], delimiter: &[u8]) -> Option<(&'a [u8], &'a [u8])> {
+    let pos = find_bytes(data, delimiter)?;
+    Some((&data[..pos], &data[pos + delimiter.len()..]))
+  }
+
+  fn field_name(headers: &str) -> Option<String> {
+    content_disposition_param(headers, "name")
+  }
+
+  fn file_name(headers: &str) -> Option<String> {
+    content_disposition_param(headers, "filename")
+  }
+
+  fn content_disposition_param(headers: &str, param: &str) -> Option<String> {
+    let prefix = format!("{}=\"", param);
+    headers.lines().find_map(|line| {
+      if !line.trim_start().starts_with("Content-Disposition:") {
+        return None;
+      }
+      let start = line.find(&prefix)? + prefix.len();
+      let end = line[start..].find('"')? + start;
+      Some(line[start..end].to_string())
+    })
+  }
+
+  fn bad_request(message: &str) -> Response<Body> {
+    let data = json!({ "error": message });
+    Response::builder()
+      .status(StatusCode::BAD_REQUEST)
+      .header(header::CONTENT_TYPE, "application/json")
+      .body(Body::from(serde_json::to_string(&data).expect("can jsonify error")))
+      .expect("can build response")
+  }
+
   async fn get_events(mut network: Client) -> Result<Response<Body>> {
     let peers = network.get_events().await;
     let data = serde_json::Value::Array(peers.into_iter().map(|(_, peer)| { 
@@ -108,6 +281,77 @@ impl AdminServer {
     Ok(response)
   }
 
+  async fn get_events_stream(mut network: Client) -> Result<Response<Body>> {
+    let receiver = network.subscribe_events().await;
+    let sse_stream = stream::unfold(receiver, |mut receiver| async move {
+      loop {
+        match receiver.recv().await {
+          Ok(event) => {
+            let frame = sse_frame(&event);
+            return Some((Ok::<_, GenericError>(frame), receiver));
+          }
+          // The broadcast channel is sized so slow subscribers miss the
+          // oldest events rather than stalling the network loop; resume
+          // from the latest event instead of dropping the connection.
+          Err(RecvError::Lagged(skipped)) => {
+            info!("SSE subscriber lagged behind, skipped {} events", skipped);
+          }
+          Err(RecvError::Closed) => return None,
+        }
+      }
+    });
+    let response = Response::builder()
+      .status(StatusCode::OK)
+      .header(header::CONTENT_TYPE, "text/event-stream")
+      .header(header::CACHE_CONTROL, "no-cache")
+      .body(Body::wrap_stream(sse_stream))?;
+    Ok(response)
+  }
+
+  fn sse_frame(event: &ListEventType) -> String {
+    let data = match event {
+      ListEventType::Response(resp) => json!({
+        "type": "peer_info",
+        "id": resp.data.id,
+        "hostname": resp.data.hostname,
+        "description": resp.data.description
+      }),
+      ListEventType::PeerLeft(peer_id) => json!({
+        "type": "peer_left",
+        "id": peer_id
+      }),
+      ListEventType::FileReceived { from, filename, accepted, reason } => json!({
+        "type": "file_received",
+        "from": from,
+        "filename": filename,
+        "accepted": accepted,
+        "reason": reason
+      }),
+    };
+    format!("data: {}\n\n", data)
+  }
+
+  async fn get_identity(mut network: Client) -> Result<Response<Body>> {
+    let peer_id = network.get_identity().await;
+    let data = json!({ "peer_id": peer_id });
+    let json = serde_json::to_string(&data)?;
+    let response = Response::builder()
+      .status(StatusCode::OK)
+      .header(header::CONTENT_TYPE, "application/json")
+      .body(Body::from(json))?;
+    Ok(response)
+  }
+
+  async fn get_metrics(mut network: Client) -> Result<Response<Body>> {
+    let metrics = network.get_metrics().await;
+    let json = serde_json::to_string(&metrics)?;
+    let response = Response::builder()
+      .status(StatusCode::OK)
+      .header(header::CONTENT_TYPE, "application/json")
+      .body(Body::from(json))?;
+    Ok(response)
+  }
+
   fn not_found() -> Response<Body> {
     Response::builder()
       .status(StatusCode::NOT_FOUND)
@@ -1,83 +1,180 @@
-use libp2p::floodsub::{Floodsub, FloodsubEvent};
+use libp2p::autonat;
+use libp2p::dcutr;
+use libp2p::gossipsub::{Gossipsub, GossipsubEvent, GossipsubMessage, MessageAcceptance};
 use libp2p::mdns::{Mdns, MdnsEvent};
+use libp2p::relay;
+use libp2p::request_response::{RequestId, RequestResponse, RequestResponseEvent, RequestResponseMessage};
+use libp2p::swarm::behaviour::toggle::Toggle;
 use libp2p::swarm::NetworkBehaviourEventProcess;
 use libp2p::NetworkBehaviour;
 use log::{error, info};
+use sha2::{Digest, Sha256};
 use tokio::sync::mpsc;
+use std::path::PathBuf;
 use crate::models::peer::Peer;
 use gethostname::gethostname;
 
+use crate::protocols::drop::DropCodec;
+use crate::protocols::peer_info::PeerInfoCodec;
+use crate::requests::drop_request::DropRequest;
 use crate::requests::list_request::{PeerListMode, PeerListRequest};
-use crate::responses::list_response::ListResponse;
+use crate::responses::drop_response::DropResponse;
+use crate::responses::list_response::{ListEventType, ListResponse};
 
 #[derive(NetworkBehaviour)]
 pub struct PeerStatusBehaviour {
-    pub floodsub: Floodsub,
+    pub peer_info: RequestResponse<PeerInfoCodec>,
+    pub drop: RequestResponse<DropCodec>,
+    pub gossipsub: Gossipsub,
     pub mdns: Mdns,
+    pub autonat: autonat::Behaviour,
+    pub relay_client: Toggle<relay::client::Behaviour>,
+    pub relay_server: Toggle<relay::Behaviour>,
+    pub dcutr: Toggle<dcutr::behaviour::Behaviour>,
     #[behaviour(ignore)]
     pub response_sender: mpsc::UnboundedSender<ListResponse>,
     #[behaviour(ignore)]
+    pub expired_sender: mpsc::UnboundedSender<String>,
+    #[behaviour(ignore)]
+    pub transfer_event_sender: mpsc::UnboundedSender<ListEventType>,
+    #[behaviour(ignore)]
+    pub drop_result_sender: mpsc::UnboundedSender<(RequestId, Result<(), String>)>,
+    #[behaviour(ignore)]
+    pub download_dir: PathBuf,
+    #[behaviour(ignore)]
     pub description: String,
     #[behaviour(ignore)]
     pub peer_id: String
 }
 
-impl NetworkBehaviourEventProcess<FloodsubEvent> for PeerStatusBehaviour {
-    fn inject_event(&mut self, event: FloodsubEvent) {
+impl NetworkBehaviourEventProcess<RequestResponseEvent<PeerListRequest, ListResponse>> for PeerStatusBehaviour {
+    fn inject_event(&mut self, event: RequestResponseEvent<PeerListRequest, ListResponse>) {
         match event {
-            FloodsubEvent::Message(msg) => {
-                info!("Receiving message.");
-                if let Ok(resp) = serde_json::from_slice::<ListResponse>(&msg.data) {
-                    info!("Got a ListResponse");
-                    if resp.receiver == self.peer_id.to_string() {
-                        info!("Response from {}:", msg.source);
-                        info!("{:?}", resp.data);
-                    } else {
-                        info!("Wasn't our ListResponse. Our id is {} and the receiver was {}", self.peer_id.to_string(), resp.receiver);
-                    }
-                } else if let Ok(req) = serde_json::from_slice::<PeerListRequest>(&msg.data) {
-                    info!("Got a PeerListRequest");
-                    match req.mode {
+            RequestResponseEvent::Message { peer, message } => match message {
+                RequestResponseMessage::Request { request, channel, .. } => {
+                    info!("Got a PeerListRequest from {:?}", peer);
+                    match request.mode {
                         PeerListMode::ALL => {
-                            info!("Received ALL req: {:?} from {:?}", req, msg.source);
-                            respond_with_peer_info(
-                                self.response_sender.clone(),
-                                msg.source.to_string(),
-                                self.peer_id.clone(),
-                                self.description.clone()
-                            );
+                            respond_with_peer_info(&mut self.peer_info, channel, self.peer_id.clone(), self.description.clone());
                         }
                         PeerListMode::One(ref peer_id) => {
-                            if peer_id == &self.peer_id.to_string() {
-                                info!("Received req: {:?} from {:?}", req, msg.source);
-                                respond_with_peer_info(
-                                    self.response_sender.clone(),
-                                    msg.source.to_string(),
-                                    self.peer_id.clone(),
-                                    self.description.clone()
-                                );
+                            if peer_id == &self.peer_id {
+                                respond_with_peer_info(&mut self.peer_info, channel, self.peer_id.clone(), self.description.clone());
                             }
                         }
                     }
                 }
+                RequestResponseMessage::Response { response, .. } => {
+                    info!("Got a ListResponse from {:?}", peer);
+                    if let Err(e) = self.response_sender.send(response) {
+                        error!("error sending response via channel, {}", e);
+                    }
+                }
+            },
+            RequestResponseEvent::OutboundFailure { peer, error, .. } => {
+                error!("peer info request to {:?} failed: {:?}", peer, error);
+            }
+            RequestResponseEvent::InboundFailure { peer, error, .. } => {
+                error!("peer info request from {:?} failed: {:?}", peer, error);
+            }
+            RequestResponseEvent::ResponseSent { .. } => (),
+        }
+    }
+}
+
+impl NetworkBehaviourEventProcess<RequestResponseEvent<DropRequest, DropResponse>> for PeerStatusBehaviour {
+    fn inject_event(&mut self, event: RequestResponseEvent<DropRequest, DropResponse>) {
+        match event {
+            RequestResponseEvent::Message { peer, message } => match message {
+                RequestResponseMessage::Request { request, channel, .. } => {
+                    info!("Got a file drop request from {:?}: {}", peer, request.filename);
+                    let (accepted, reason) = self.save_incoming_file(&request);
+                    let resp = DropResponse { accepted, reason: reason.clone() };
+                    if self.drop.send_response(channel, resp).is_err() {
+                        error!("error sending drop response, response channel closed");
+                    }
+                    let notice = ListEventType::FileReceived {
+                        from: peer.to_string(),
+                        filename: request.filename,
+                        accepted,
+                        reason,
+                    };
+                    if let Err(e) = self.transfer_event_sender.send(notice) {
+                        error!("error sending inbound-transfer notice via channel, {}", e);
+                    }
+                }
+                RequestResponseMessage::Response { request_id, response } => {
+                    info!("Got a drop response from {:?}: accepted={}", peer, response.accepted);
+                    let result = if response.accepted {
+                        Ok(())
+                    } else {
+                        Err(response.reason.unwrap_or_else(|| "rejected by peer".to_string()))
+                    };
+                    if let Err(e) = self.drop_result_sender.send((request_id, result)) {
+                        error!("error sending drop result via channel, {}", e);
+                    }
+                }
+            },
+            RequestResponseEvent::OutboundFailure { request_id, error, .. } => {
+                let _ = self.drop_result_sender.send((request_id, Err(format!("{:?}", error))));
+            }
+            RequestResponseEvent::InboundFailure { peer, error, .. } => {
+                error!("drop request from {:?} failed: {:?}", peer, error);
+            }
+            RequestResponseEvent::ResponseSent { .. } => (),
+        }
+    }
+}
+
+impl NetworkBehaviourEventProcess<GossipsubEvent> for PeerStatusBehaviour {
+    fn inject_event(&mut self, event: GossipsubEvent) {
+        if let GossipsubEvent::Message { propagation_source, message_id, message } = event {
+            let acceptance = validate_announcement(&message);
+            if acceptance == MessageAcceptance::Accept {
+                if let Ok(resp) = serde_json::from_slice::<ListResponse>(&message.data) {
+                    if let Err(e) = self.response_sender.send(resp) {
+                        error!("error sending gossipsub announcement via channel, {}", e);
+                    }
+                }
+            } else {
+                info!("rejecting undeserializable gossipsub message from {:?}", propagation_source);
+            }
+            if self
+                .gossipsub
+                .report_message_validation_result(&message_id, &propagation_source, acceptance)
+                .is_err()
+            {
+                error!("message {:?} was not in the validation cache anymore", message_id);
             }
-            _ => (),
         }
     }
 }
 
+fn validate_announcement(message: &GossipsubMessage) -> MessageAcceptance {
+    if serde_json::from_slice::<ListResponse>(&message.data).is_ok()
+        || serde_json::from_slice::<PeerListRequest>(&message.data).is_ok()
+    {
+        MessageAcceptance::Accept
+    } else {
+        MessageAcceptance::Reject
+    }
+}
+
 impl NetworkBehaviourEventProcess<MdnsEvent> for PeerStatusBehaviour {
     fn inject_event(&mut self, event: MdnsEvent) {
         match event {
             MdnsEvent::Discovered(discovered_list) => {
                 for (peer, _addr) in discovered_list {
-                    self.floodsub.add_node_to_partial_view(peer);
+                    self.gossipsub.add_explicit_peer(&peer);
                 }
             }
             MdnsEvent::Expired(expired_list) => {
                 for (peer, _addr) in expired_list {
                     if !self.mdns.has_node(&peer) {
-                        self.floodsub.remove_node_from_partial_view(&peer);
+                        self.gossipsub.remove_explicit_peer(&peer);
+                        if let Err(e) = self.expired_sender.send(peer.to_string()) {
+                            error!("error sending expired-peer notice via channel, {}", e);
+                        }
                     }
                 }
             }
@@ -85,17 +182,94 @@ impl NetworkBehaviourEventProcess<MdnsEvent> for PeerStatusBehaviour {
     }
 }
 
-fn respond_with_peer_info(sender: mpsc::UnboundedSender<ListResponse>, receiver: String, peer_id: String, description: String) {
+impl NetworkBehaviourEventProcess<autonat::Event> for PeerStatusBehaviour {
+    fn inject_event(&mut self, event: autonat::Event) {
+        info!("AutoNAT event: {:?}", event);
+    }
+}
+
+impl NetworkBehaviourEventProcess<relay::client::Event> for PeerStatusBehaviour {
+    fn inject_event(&mut self, event: relay::client::Event) {
+        info!("Relay client event: {:?}", event);
+    }
+}
+
+impl NetworkBehaviourEventProcess<relay::Event> for PeerStatusBehaviour {
+    fn inject_event(&mut self, event: relay::Event) {
+        info!("Relay server event: {:?}", event);
+    }
+}
+
+impl NetworkBehaviourEventProcess<dcutr::behaviour::Event> for PeerStatusBehaviour {
+    fn inject_event(&mut self, event: dcutr::behaviour::Event) {
+        info!("DCUtR hole-punch event: {:?}", event);
+    }
+}
+
+impl PeerStatusBehaviour {
+    // Writes an inbound file drop to download_dir, verifying its sha256 digest.
+    fn save_incoming_file(&self, request: &DropRequest) -> (bool, Option<String>) {
+        if let Some(reason) = invalid_filename_reason(&request.filename) {
+            error!("rejecting incoming file with unsafe filename {:?}: {}", request.filename, reason);
+            return (false, Some(format!("unsafe filename: {}", reason)));
+        }
+
+        let mut hasher = Sha256::new();
+        hasher.update(&request.data);
+        let digest = format!("{:x}", hasher.finalize());
+
+        if digest != request.sha256 {
+            error!("sha256 mismatch for incoming file {}: expected {}, got {}", request.filename, request.sha256, digest);
+            return (false, Some("sha256 mismatch".to_string()));
+        }
+
+        if let Err(e) = std::fs::create_dir_all(&self.download_dir) {
+            error!("error creating download directory, {}", e);
+            return (false, Some(format!("could not create download directory: {}", e)));
+        }
+
+        let dest = self.download_dir.join(&request.filename);
+        match std::fs::write(&dest, &request.data) {
+            Ok(()) => (true, None),
+            Err(e) => {
+                error!("error writing incoming file to {}, {}", dest.display(), e);
+                (false, Some(format!("could not write file: {}", e)))
+            }
+        }
+    }
+}
+
+// Rejects filenames that are unsafe to join onto download_dir: absolute,
+// empty, ".", "..", or containing a path separator.
+fn invalid_filename_reason(filename: &str) -> Option<&'static str> {
+    use std::path::{Component, Path};
+
+    if filename.is_empty() {
+        return Some("filename is empty");
+    }
+
+    let mut components = Path::new(filename).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => None,
+        _ => Some("filename must be a single path component"),
+    }
+}
+
+fn respond_with_peer_info(
+    peer_info: &mut RequestResponse<PeerInfoCodec>,
+    channel: libp2p::request_response::ResponseChannel<ListResponse>,
+    peer_id: String,
+    description: String
+) {
     let resp = ListResponse {
         mode: PeerListMode::ALL,
-        receiver,
         data: Peer {
             id: peer_id,
             hostname: gethostname().to_str().unwrap().to_string(),
             description
         },
     };
-    if let Err(e) = sender.send(resp) {
-        error!("error sending response via channel, {}", e);
+    if peer_info.send_response(channel, resp).is_err() {
+        error!("error sending peer info response, response channel closed");
     }
 }
@@ -0,0 +1,42 @@
+use libp2p::identity;
+use log::info;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Default location for the persisted node identity, relative to $HOME.
+const DEFAULT_IDENTITY_PATH: &str = ".config/p2p-status/key";
+
+pub fn default_identity_path() -> PathBuf {
+    match std::env::var_os("HOME") {
+        Some(home) => PathBuf::from(home).join(DEFAULT_IDENTITY_PATH),
+        None => PathBuf::from(DEFAULT_IDENTITY_PATH),
+    }
+}
+
+// Loads the ed25519 keypair at `path`, generating and persisting a fresh one
+// if no key file exists yet.
+pub fn load_or_generate(path: &Path) -> identity::Keypair {
+    match fs::read(path) {
+        Ok(bytes) => {
+            info!("Loading node identity from {}", path.display());
+            identity::Keypair::from_protobuf_encoding(&bytes).expect("key file is a valid protobuf-encoded keypair")
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            info!("No identity found at {}, generating a new one", path.display());
+            let keypair = identity::Keypair::generate_ed25519();
+            write_atomically(path, &keypair.to_protobuf_encoding().expect("can encode keypair"));
+            keypair
+        }
+        Err(e) => panic!("could not read identity file {}: {}", path.display(), e),
+    }
+}
+
+fn write_atomically(path: &Path, bytes: &[u8]) {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).expect("can create identity directory");
+    }
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, bytes).expect("can write identity key to temp file");
+    fs::rename(&tmp_path, path).expect("can move identity key into place");
+}
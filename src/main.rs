@@ -1,7 +1,10 @@
 
+use std::path::PathBuf;
+
 use clap::Parser;
 use p2p::admin::admin_server::AdminServer;
-use p2p::network::p2p_network::P2PNetwork;
+use p2p::identity::keypair_store;
+use p2p::network::p2p_network::{default_download_dir, P2PNetwork};
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
@@ -9,7 +12,22 @@ struct Args {
     #[clap(short, long, value_parser, default_value = "")]
     description: String,
     #[clap(short, long, value_parser, default_value = "3000")]
-    admin_port: u16
+    admin_port: u16,
+    /// Multiaddr of a relay node to use for NAT traversal, e.g. /ip4/1.2.3.4/tcp/4001/p2p/<relay peer id>
+    #[clap(long, value_parser)]
+    relay_addr: Option<String>,
+    /// Run this node as a relay server for other peers instead of as a regular peer
+    #[clap(long, value_parser, default_value = "false")]
+    relay_server: bool,
+    /// Path to the node's persisted identity keypair. Defaults to ~/.config/p2p-status/key
+    #[clap(long, value_parser)]
+    identity_path: Option<PathBuf>,
+    /// Maximum number of simultaneous connections this node will accept
+    #[clap(long, value_parser, default_value = "100")]
+    max_connections: u32,
+    /// Directory incoming file drops are saved to. Defaults to ~/.config/p2p-status/downloads
+    #[clap(long, value_parser)]
+    download_dir: Option<PathBuf>
 }
 
 #[tokio::main]
@@ -19,8 +37,14 @@ async fn main() {
     // parse arguments
     let args = Args::parse();
 
+    let relay_addr = args.relay_addr.map(|addr| addr.parse().expect("relay-addr must be a valid multiaddr"));
+
+    let identity_path = args.identity_path.unwrap_or_else(keypair_store::default_identity_path);
+    let keypair = keypair_store::load_or_generate(&identity_path);
+    let download_dir = args.download_dir.unwrap_or_else(default_download_dir);
+
     // initialize the p2p network
-    let mut p2p_network = P2PNetwork::new(args.description).await;
+    let mut p2p_network = P2PNetwork::new(args.description, keypair, relay_addr, args.relay_server, args.max_connections, download_dir).await;
 
     let p2p_client = p2p_network.client.clone();
 
@@ -0,0 +1,9 @@
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+pub struct NetworkMetrics {
+    pub bytes_inbound: u64,
+    pub bytes_outbound: u64,
+    pub connections: usize,
+    pub discovered_peers: usize,
+}
@@ -1,75 +1,178 @@
 use libp2p::{
-    core::{upgrade, either::EitherOutput},
-    floodsub::{Floodsub, Topic, FloodsubRpc, protocol::FloodsubProtocol},
+    autonat,
+    bandwidth::{BandwidthLogging, BandwidthSinks},
+    core::{upgrade, transport::OrTransport},
+    dcutr,
     futures::{StreamExt},
+    gossipsub::{Gossipsub, GossipsubConfigBuilder, GossipsubMessage, IdentTopic, MessageAuthenticity, MessageId, ValidationMode},
     identity,
     mdns::{Mdns},
     mplex,
+    multiaddr::Protocol,
     noise::{Keypair, NoiseConfig, X25519Spec},
-    PeerId,
-    swarm::{Swarm, SwarmBuilder, ExpandedSwarm, IntoProtocolsHandlerSelect, protocols_handler::DummyProtocolsHandler, OneShotHandler}, tcp::TokioTcpConfig, Transport,
+    relay,
+    request_response::{ProtocolSupport, RequestResponse, RequestResponseConfig, RequestId},
+    swarm::{behaviour::toggle::Toggle, ConnectionLimits, Swarm, SwarmBuilder, SwarmEvent},
+    Multiaddr, PeerId,
+    tcp::TokioTcpConfig, Transport,
 };
-use tokio::{io::AsyncBufReadExt, sync::{mpsc::{self, UnboundedReceiver, Receiver, Sender}, oneshot}};
+use sha2::{Digest, Sha256};
+use tokio::sync::{broadcast, mpsc::{self, UnboundedReceiver, Receiver, Sender}, oneshot};
 use once_cell::sync::Lazy;
-use std::{collections::{HashSet, HashMap}, error::Error};
+use std::{
+    collections::{HashSet, HashMap, hash_map::DefaultHasher},
+    hash::{Hash, Hasher},
+    iter, error::Error,
+    path::PathBuf,
+    sync::Arc
+};
 
 use clap::Parser;
+use gethostname::gethostname;
 use log::{error, info};
 
-use crate::{behaviors::peer_status::PeerStatusBehaviour, requests::message_request::MessageRequest, admin::admin_server::AdminServer, network, responses::list_response::ListResponse, models::peer::Peer};
+use crate::{behaviors::peer_status::PeerStatusBehaviour, admin::admin_server::AdminServer, network, responses::list_response::ListResponse, models::peer::Peer};
+use crate::models::metrics::NetworkMetrics;
+use crate::protocols::drop::{DropCodec, DropProtocol};
+use crate::protocols::peer_info::{PeerInfoCodec, PeerInfoProtocol};
+use crate::requests::drop_request::DropRequest;
 use crate::requests::list_request::{PeerListMode, PeerListRequest};
 use crate::responses::list_response::ListEventType;
 
+// Capacity of the SSE broadcast channel; slow subscribers that fall this far
+// behind simply miss the oldest events rather than stalling the network loop.
+const EVENT_BROADCAST_CAPACITY: usize = 64;
+
 pub struct P2PNetwork {
     swarm: Swarm<PeerStatusBehaviour>,
     receiver: UnboundedReceiver<ListResponse>,
+    expired_receiver: UnboundedReceiver<String>,
     command_receiver: Receiver<Command>,
     pub client: Client,
-    peer_info_responses: HashMap<String, Peer>
+    peer_info_responses: HashMap<String, Peer>,
+    local_peer_id: PeerId,
+    event_broadcaster: broadcast::Sender<ListEventType>,
+    bandwidth_sinks: Arc<BandwidthSinks>,
+    drop_result_receiver: UnboundedReceiver<(RequestId, Result<(), String>)>,
+    transfer_event_receiver: UnboundedReceiver<ListEventType>,
+    pending_file_sends: HashMap<RequestId, oneshot::Sender<Result<(), String>>>,
+    download_dir: PathBuf
 }
 
-static KEYS: Lazy<identity::Keypair> = Lazy::new(|| identity::Keypair::generate_ed25519());
-static PEER_ID: Lazy<PeerId> = Lazy::new(|| PeerId::from(KEYS.public()));
-static TOPIC: Lazy<Topic> = Lazy::new(|| Topic::new("recipes"));
+static ANNOUNCEMENT_TOPIC: Lazy<IdentTopic> = Lazy::new(|| IdentTopic::new("p2p-status-announcements"));
+
+// Default location incoming file drops are written to, relative to $HOME.
+const DEFAULT_DOWNLOAD_DIR: &str = ".config/p2p-status/downloads";
+
+pub fn default_download_dir() -> PathBuf {
+    match std::env::var_os("HOME") {
+        Some(home) => PathBuf::from(home).join(DEFAULT_DOWNLOAD_DIR),
+        None => PathBuf::from(DEFAULT_DOWNLOAD_DIR),
+    }
+}
+
+fn announcement_message_id(message: &GossipsubMessage) -> MessageId {
+    let mut hasher = DefaultHasher::new();
+    message.data.hash(&mut hasher);
+    MessageId::from(hasher.finish().to_string())
+}
 
 impl P2PNetwork {
-    pub async fn new(description: String) -> P2PNetwork {
-        info!("Peer Id: {}", PEER_ID.clone());
+    pub async fn new(
+        description: String,
+        keypair: identity::Keypair,
+        relay_addr: Option<Multiaddr>,
+        relay_server: bool,
+        max_connections: u32,
+        download_dir: PathBuf
+    ) -> P2PNetwork {
+        let local_peer_id = PeerId::from(keypair.public());
+        info!("Peer Id: {}", local_peer_id);
 
         let (response_sender, mut response_rcv) = mpsc::unbounded_channel();
+        let (expired_sender, expired_receiver) = mpsc::unbounded_channel();
+        let (transfer_event_sender, transfer_event_receiver) = mpsc::unbounded_channel();
+        let (drop_result_sender, drop_result_receiver) = mpsc::unbounded_channel();
+        let (event_broadcaster, _) = broadcast::channel(EVENT_BROADCAST_CAPACITY);
 
         let auth_keys = Keypair::<X25519Spec>::new()
-            .into_authentic(&KEYS)
+            .into_authentic(&keypair)
             .expect("can create auth keys");
 
-        let transp = TokioTcpConfig::new()
-            .upgrade(upgrade::Version::V1)
-            .authenticate(NoiseConfig::xx(auth_keys).into_authenticated()) // XX Handshake pattern, IX exists as well and IK - only XX currently provides interop with other libp2p impls
-            .multiplex(mplex::MplexConfig::new())
-            .boxed();
+        let (relay_transport, relay_client_behaviour) = relay::client::new(local_peer_id);
+
+        let tcp_transport = TokioTcpConfig::new();
+        let (transp, bandwidth_sinks) = BandwidthLogging::new(
+            OrTransport::new(relay_transport, tcp_transport)
+                .upgrade(upgrade::Version::V1)
+                .authenticate(NoiseConfig::xx(auth_keys).into_authenticated()) // XX Handshake pattern, IX exists as well and IK - only XX currently provides interop with other libp2p impls
+                .multiplex(mplex::MplexConfig::new())
+                .boxed()
+        );
+        let transp = transp.boxed();
 
         info!("Description: {}", description);
 
-        let mut behaviour = PeerStatusBehaviour {
-            floodsub: Floodsub::new(PEER_ID.clone()),
+        let peer_info = RequestResponse::new(
+            PeerInfoCodec(),
+            iter::once((PeerInfoProtocol(), ProtocolSupport::Full)),
+            RequestResponseConfig::default()
+        );
+
+        let gossipsub_config = GossipsubConfigBuilder::default()
+            .validation_mode(ValidationMode::Strict)
+            .message_id_fn(announcement_message_id)
+            .build()
+            .expect("valid gossipsub config");
+
+        let mut gossipsub = Gossipsub::new(MessageAuthenticity::Signed(keypair.clone()), gossipsub_config)
+            .expect("can create gossipsub");
+        gossipsub
+            .subscribe(&ANNOUNCEMENT_TOPIC)
+            .expect("can subscribe to announcement topic");
+
+        let autonat = autonat::Behaviour::new(local_peer_id, autonat::Config::default());
+
+        // In relay-server mode this node runs the relay itself and doesn't need
+        // a relay client or hole-punching; regular peers run the client + DCUtR
+        // and dial out through `--relay-addr` to traverse NATs.
+        let drop = RequestResponse::new(
+            DropCodec(),
+            iter::once((DropProtocol(), ProtocolSupport::Full)),
+            RequestResponseConfig::default()
+        );
+
+        let behaviour = PeerStatusBehaviour {
+            peer_info,
+            drop,
+            gossipsub,
             mdns: Mdns::new(Default::default())
                 .await
                 .expect("can create mdns"),
+            autonat,
+            relay_client: Toggle::from((!relay_server).then(|| relay_client_behaviour)),
+            relay_server: Toggle::from(relay_server.then(|| relay::Behaviour::new(local_peer_id, Default::default()))),
+            dcutr: Toggle::from((!relay_server).then(|| dcutr::behaviour::Behaviour::new())),
             response_sender,
+            expired_sender,
+            transfer_event_sender,
+            drop_result_sender,
+            download_dir: download_dir.clone(),
             description: description,
-            peer_id: format!("{}", *PEER_ID)
+            peer_id: format!("{}", local_peer_id)
         };
 
-        behaviour.floodsub.subscribe(TOPIC.clone());
+        let connection_limits = ConnectionLimits::default()
+            .with_max_established_per_peer(Some(1))
+            .with_max_established(Some(max_connections));
 
-        let mut swarm = SwarmBuilder::new(transp, behaviour, PEER_ID.clone())
+        let mut swarm = SwarmBuilder::new(transp, behaviour, local_peer_id)
+            .connection_limits(connection_limits)
             .executor(Box::new(|fut| {
                 tokio::spawn(fut);
             }))
             .build();
 
-        let mut stdin = tokio::io::BufReader::new(tokio::io::stdin()).lines();
-
         Swarm::listen_on(
             &mut swarm,
             "/ip4/0.0.0.0/tcp/0"
@@ -78,16 +181,30 @@ impl P2PNetwork {
         )
             .expect("swarm can be started");
 
+        if let Some(addr) = relay_addr {
+            info!("Dialing relay {} to obtain a relayed listen address", addr);
+            Swarm::listen_on(&mut swarm, addr.with(Protocol::P2pCircuit))
+                .expect("can listen on relayed address");
+        }
+
         let (command_sender, command_receiver) = mpsc::channel(1);
 
         Self {
             swarm: swarm,
             receiver: response_rcv,
+            expired_receiver,
             command_receiver: command_receiver,
             client: Client {
                 sender: command_sender
             },
-            peer_info_responses: HashMap::new()
+            peer_info_responses: HashMap::new(),
+            local_peer_id,
+            event_broadcaster,
+            bandwidth_sinks,
+            drop_result_receiver,
+            transfer_event_receiver,
+            pending_file_sends: HashMap::new(),
+            download_dir
         }
 
     }
@@ -100,14 +217,30 @@ impl P2PNetwork {
                         Some(e) => self.handle_event(e).await,
                         None => return,
                     },
+                    expired = self.expired_receiver.recv() => match expired {
+                        Some(peer_id) => self.handle_peer_expired(peer_id).await,
+                        None => return,
+                    },
                     event = self.swarm.select_next_some() => {
-                        info!("Unhandled Swarm Event: {:?}", event);
-                        ()
+                        if let SwarmEvent::ConnectionEstablished { peer_id, .. } = &event {
+                            info!("Connection established with {}, announcing self", peer_id);
+                            self.announce_self().await;
+                        } else {
+                            info!("Unhandled Swarm Event: {:?}", event);
+                        }
                     },
                     command = self.command_receiver.recv() => match command {
                         Some(c) => self.handle_command(c).await,
                         None => return,
                     },
+                    drop_result = self.drop_result_receiver.recv() => match drop_result {
+                        Some((request_id, result)) => self.handle_drop_result(request_id, result),
+                        None => return,
+                    },
+                    transfer_event = self.transfer_event_receiver.recv() => match transfer_event {
+                        Some(event) => { let _ = self.event_broadcaster.send(event); },
+                        None => return,
+                    },
                 }
             };
         }
@@ -140,52 +273,104 @@ impl P2PNetwork {
             Command::GetPeerInfoResponses { sender } => {
                 info!("getting peer info responses");
                 sender.send(self.peer_info_responses.clone()).expect("result to be present");
+            },
+            Command::GetIdentity { sender } => {
+                info!("getting local peer id");
+                sender.send(self.local_peer_id.to_string()).expect("result to be present");
+            },
+            Command::Subscribe { sender } => {
+                info!("subscribing to peer event stream");
+                sender.send(self.event_broadcaster.subscribe()).expect("result to be present");
+            },
+            Command::GetMetrics { sender } => {
+                info!("getting network metrics");
+                let metrics = NetworkMetrics {
+                    bytes_inbound: self.bandwidth_sinks.total_inbound(),
+                    bytes_outbound: self.bandwidth_sinks.total_outbound(),
+                    connections: self.swarm.network_info().num_connections(),
+                    discovered_peers: self.handle_list_peers().await.len()
+                };
+                sender.send(metrics).expect("result to be present");
+            },
+            Command::SendFile { target_peer, filename, data, sender } => {
+                info!("sending file {} ({} bytes) to {}", filename, data.len(), target_peer);
+                let result = self.send_file(target_peer, filename, data).await;
+                let _ = sender.send(result);
             }
         }
     }
 
+    // Stores a ListResponse locally; never re-published to gossipsub, which
+    // would turn point-to-point peer_info replies into a network-wide flood.
     async fn handle_event(&mut self, event: ListResponse) {
         info!("Received response!");
-        let json = serde_json::to_string(&event).expect("can jsonify response");
-        self.swarm
-            .behaviour_mut()
-            .floodsub
-            .publish(TOPIC.clone(), json.as_bytes());
+        let _ = self.event_broadcaster.send(ListEventType::Response(event.clone()));
         self.peer_info_responses.insert(event.data.id.clone(), event.data);
     }
 
-    async fn send_peer_list_request(&mut self) {
-        let req = PeerListRequest {
-            mode: PeerListMode::ALL
+    // Announces this node's own info over the gossipsub announcement topic,
+    // so newly connected peers (including relayed ones mdns can't see) learn
+    // about it without a direct peer_info round trip.
+    async fn announce_self(&mut self) {
+        let resp = ListResponse {
+            mode: PeerListMode::ALL,
+            data: Peer {
+                id: self.swarm.behaviour().peer_id.clone(),
+                hostname: gethostname().to_str().unwrap().to_string(),
+                description: self.swarm.behaviour().description.clone(),
+            },
         };
-        let json = serde_json::to_string(&req).expect("can jsonify request");
-        self.swarm
-            .behaviour_mut()
-            .floodsub
-            .publish(TOPIC.clone(), json.as_bytes());
+        let json = serde_json::to_string(&resp).expect("can jsonify response");
+        if let Err(e) = self.swarm.behaviour_mut().gossipsub.publish(ANNOUNCEMENT_TOPIC.clone(), json.as_bytes()) {
+            error!("error announcing self over gossipsub, {:?}", e);
+        }
     }
-}
 
+    async fn handle_peer_expired(&mut self, peer_id: String) {
+        info!("Peer {} expired", peer_id);
+        self.peer_info_responses.remove(&peer_id);
+        let _ = self.event_broadcaster.send(ListEventType::PeerLeft(peer_id));
+    }
 
+    async fn send_peer_list_request(&mut self) {
+        let peers = self.handle_list_peers().await;
+        for peer in peers {
+            let req = PeerListRequest { mode: PeerListMode::ALL };
+            self.swarm.behaviour_mut().peer_info.send_request(&peer, req);
+        }
+    }
+
+    fn handle_drop_result(&mut self, request_id: RequestId, result: Result<(), String>) {
+        if let Some(sender) = self.pending_file_sends.remove(&request_id) {
+            let _ = sender.send(result);
+        }
+    }
+
+    async fn send_file(&mut self, target_peer: String, filename: String, data: Vec<u8>) -> Result<(), String> {
+        let peer = self
+            .handle_list_peers()
+            .await
+            .into_iter()
+            .find(|peer| peer.to_string() == target_peer)
+            .ok_or_else(|| format!("peer {} not found among discovered peers", target_peer))?;
+
+        let mut hasher = Sha256::new();
+        hasher.update(&data);
+        let sha256 = format!("{:x}", hasher.finalize());
+
+        let req = DropRequest {
+            filename,
+            size: data.len() as u64,
+            sha256,
+            data,
+        };
+
+        let (sender, receiver) = oneshot::channel();
+        let request_id = self.swarm.behaviour_mut().drop.send_request(&peer, req);
+        self.pending_file_sends.insert(request_id, sender);
 
-async fn handle_message_send(cmd: &str, swarm: &mut Swarm<PeerStatusBehaviour>) {
-    let rest = cmd.strip_prefix("send ");
-    match rest {
-      Some(message_content) => {
-          let req = MessageRequest {
-              message: String::from(message_content),
-              hostname: gethostname::gethostname().to_str().unwrap().to_string()
-          };
-          let json = serde_json::to_string(&req).expect("can jsonify request");
-          swarm
-              .behaviour_mut()
-              .floodsub
-              .publish(TOPIC.clone(), json.as_bytes());
-      }
-      None => {
-          info!("Message required.");
-      }
-  };
+        receiver.await.map_err(|_| "drop result channel closed".to_string())?
+    }
 }
 
 #[derive(Debug)]
@@ -198,6 +383,21 @@ enum Command {
     },
     GetPeerInfoResponses {
         sender: oneshot::Sender<HashMap<String, Peer>>
+    },
+    GetIdentity {
+        sender: oneshot::Sender<String>
+    },
+    Subscribe {
+        sender: oneshot::Sender<broadcast::Receiver<ListEventType>>
+    },
+    GetMetrics {
+        sender: oneshot::Sender<NetworkMetrics>
+    },
+    SendFile {
+        target_peer: String,
+        filename: String,
+        data: Vec<u8>,
+        sender: oneshot::Sender<Result<(), String>>
     }
 }
 
@@ -227,4 +427,32 @@ impl Client {
         self.sender.send(Command::GetPeerInfoResponses { sender }).await.expect("Command receiver not to be dropped.");
         receiver.await.expect("Sender not to be dropped.")
     }
+
+    pub async fn get_identity(&mut self) -> String {
+        info!("getting local identity");
+        let (sender, receiver) = oneshot::channel();
+        self.sender.send(Command::GetIdentity { sender }).await.expect("Command receiver not to be dropped.");
+        receiver.await.expect("Sender not to be dropped.")
+    }
+
+    pub async fn subscribe_events(&mut self) -> broadcast::Receiver<ListEventType> {
+        info!("subscribing to peer event stream");
+        let (sender, receiver) = oneshot::channel();
+        self.sender.send(Command::Subscribe { sender }).await.expect("Command receiver not to be dropped.");
+        receiver.await.expect("Sender not to be dropped.")
+    }
+
+    pub async fn get_metrics(&mut self) -> NetworkMetrics {
+        info!("getting network metrics");
+        let (sender, receiver) = oneshot::channel();
+        self.sender.send(Command::GetMetrics { sender }).await.expect("Command receiver not to be dropped.");
+        receiver.await.expect("Sender not to be dropped.")
+    }
+
+    pub async fn send_file(&mut self, target_peer: String, filename: String, data: Vec<u8>) -> Result<(), String> {
+        info!("sending file to {}", target_peer);
+        let (sender, receiver) = oneshot::channel();
+        self.sender.send(Command::SendFile { target_peer, filename, data, sender }).await.expect("Command receiver not to be dropped.");
+        receiver.await.expect("Sender not to be dropped.")
+    }
 }
\ No newline at end of file
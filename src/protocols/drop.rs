@@ -0,0 +1,121 @@
+use async_trait::async_trait;
+use futures::prelude::*;
+use libp2p::core::upgrade::{read_length_prefixed, write_length_prefixed};
+use libp2p::request_response::{ProtocolName, RequestResponseCodec};
+use serde::{Deserialize, Serialize};
+use std::io;
+
+use crate::requests::drop_request::DropRequest;
+use crate::responses::drop_response::DropResponse;
+
+// Maximum size (in bytes) of a single chunk on the wire. Keeping this small
+// bounds per-read memory use regardless of the overall file size.
+const CHUNK_SIZE: usize = 64 * 1024;
+const MAX_METADATA_SIZE: usize = 4096;
+const MAX_RESPONSE_SIZE: usize = 4096;
+// Hard ceiling on the total size of a single drop transfer; metadata.size is
+// attacker-controlled and must be checked against this before it's used to
+// size an allocation or accepted as the accumulated read length.
+pub(crate) const MAX_TRANSFER_SIZE: u64 = 512 * 1024 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct DropProtocol();
+
+impl ProtocolName for DropProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        "/p2p-status/drop/1.0.0".as_bytes()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct DropMetadata {
+    filename: String,
+    size: u64,
+    sha256: String,
+}
+
+#[derive(Clone)]
+pub struct DropCodec();
+
+#[async_trait]
+impl RequestResponseCodec for DropCodec {
+    type Protocol = DropProtocol;
+    type Request = DropRequest;
+    type Response = DropResponse;
+
+    async fn read_request<T>(&mut self, _: &DropProtocol, io: &mut T) -> io::Result<DropRequest>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let metadata_bytes = read_length_prefixed(io, MAX_METADATA_SIZE).await?;
+        let metadata: DropMetadata = serde_json::from_slice(&metadata_bytes)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        if metadata.size > MAX_TRANSFER_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "declared transfer size {} exceeds max {}",
+                    metadata.size, MAX_TRANSFER_SIZE
+                ),
+            ));
+        }
+
+        let mut data = Vec::with_capacity(metadata.size as usize);
+        loop {
+            let chunk = read_length_prefixed(io, CHUNK_SIZE).await?;
+            if chunk.is_empty() {
+                break;
+            }
+            if data.len() + chunk.len() > metadata.size as usize
+                || data.len() + chunk.len() > MAX_TRANSFER_SIZE as usize
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "received more data than declared transfer size",
+                ));
+            }
+            data.extend_from_slice(&chunk);
+        }
+
+        Ok(DropRequest {
+            filename: metadata.filename,
+            size: metadata.size,
+            sha256: metadata.sha256,
+            data,
+        })
+    }
+
+    async fn read_response<T>(&mut self, _: &DropProtocol, io: &mut T) -> io::Result<DropResponse>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let bytes = read_length_prefixed(io, MAX_RESPONSE_SIZE).await?;
+        serde_json::from_slice(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    async fn write_request<T>(&mut self, _: &DropProtocol, io: &mut T, req: DropRequest) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        let metadata = DropMetadata {
+            filename: req.filename,
+            size: req.size,
+            sha256: req.sha256,
+        };
+        write_length_prefixed(io, serde_json::to_vec(&metadata)?).await?;
+
+        for chunk in req.data.chunks(CHUNK_SIZE) {
+            write_length_prefixed(io, chunk).await?;
+        }
+        write_length_prefixed(io, []).await?;
+        Ok(())
+    }
+
+    async fn write_response<T>(&mut self, _: &DropProtocol, io: &mut T, resp: DropResponse) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_length_prefixed(io, serde_json::to_vec(&resp)?).await
+    }
+}
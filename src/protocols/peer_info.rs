@@ -0,0 +1,62 @@
+use async_trait::async_trait;
+use futures::prelude::*;
+use libp2p::core::upgrade::{read_length_prefixed, write_length_prefixed};
+use libp2p::request_response::{ProtocolName, RequestResponseCodec};
+use std::io;
+
+use crate::requests::list_request::PeerListRequest;
+use crate::responses::list_response::ListResponse;
+
+// Maximum size (in bytes) of a single peer-info request/response frame.
+const MAX_FRAME_SIZE: usize = 1_000_000;
+
+#[derive(Debug, Clone)]
+pub struct PeerInfoProtocol();
+
+impl ProtocolName for PeerInfoProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        "/p2p-status/peerinfo/1.0.0".as_bytes()
+    }
+}
+
+#[derive(Clone)]
+pub struct PeerInfoCodec();
+
+#[async_trait]
+impl RequestResponseCodec for PeerInfoCodec {
+    type Protocol = PeerInfoProtocol;
+    type Request = PeerListRequest;
+    type Response = ListResponse;
+
+    async fn read_request<T>(&mut self, _: &PeerInfoProtocol, io: &mut T) -> io::Result<PeerListRequest>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let bytes = read_length_prefixed(io, MAX_FRAME_SIZE).await?;
+        serde_json::from_slice(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    async fn read_response<T>(&mut self, _: &PeerInfoProtocol, io: &mut T) -> io::Result<ListResponse>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let bytes = read_length_prefixed(io, MAX_FRAME_SIZE).await?;
+        serde_json::from_slice(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    async fn write_request<T>(&mut self, _: &PeerInfoProtocol, io: &mut T, req: PeerListRequest) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        let bytes = serde_json::to_vec(&req)?;
+        write_length_prefixed(io, bytes).await
+    }
+
+    async fn write_response<T>(&mut self, _: &PeerInfoProtocol, io: &mut T, resp: ListResponse) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        let bytes = serde_json::to_vec(&resp)?;
+        write_length_prefixed(io, bytes).await
+    }
+}
@@ -0,0 +1,7 @@
+#[derive(Debug)]
+pub struct DropRequest {
+    pub filename: String,
+    pub size: u64,
+    pub sha256: String,
+    pub data: Vec<u8>,
+}
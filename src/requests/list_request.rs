@@ -1,7 +1,7 @@
 use serde::Serialize;
 use serde::Deserialize;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum PeerListMode {
     ALL,
     One(String),
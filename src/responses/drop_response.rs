@@ -1,8 +1,8 @@
-use serde::Serialize;
 use serde::Deserialize;
+use serde::Serialize;
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct MessageRequest {
-    pub message: String,
-    pub hostname: String
-}
\ No newline at end of file
+pub struct DropResponse {
+    pub accepted: bool,
+    pub reason: Option<String>,
+}
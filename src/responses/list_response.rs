@@ -4,13 +4,21 @@ use serde::Serialize;
 use crate::models::peer::Peer;
 use crate::requests::list_request::PeerListMode;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ListResponse {
     pub mode: PeerListMode,
     pub data: Peer,
-    pub receiver: String,
 }
 
+// Events pushed to admin frontends over the SSE stream.
+#[derive(Debug, Clone)]
 pub enum ListEventType {
     Response(ListResponse),
+    PeerLeft(String),
+    FileReceived {
+        from: String,
+        filename: String,
+        accepted: bool,
+        reason: Option<String>,
+    },
 }